@@ -0,0 +1,513 @@
+// Copyright 2020-2021 the .NET Foundation
+// Licensed under the MIT License
+
+//! A minimal client for the Docker Engine HTTP API.
+//!
+//! Rather than shelling out to the `docker` CLI and scraping its output, the
+//! primary run and pull operations talk directly to the daemon over its Unix
+//! socket (`DOCKER_HOST=unix://...`, or the default socket path; TCP hosts
+//! are rejected, since they almost always require TLS that this client
+//! doesn't implement). The design follows shiplift's split into a transport
+//! layer ([`Transport`]) and typed container/image helpers, but stays
+//! synchronous to match the rest of the frontend: the protocol we need is
+//! simple enough that a hand-rolled HTTP/1.1 exchange is clearer than pulling
+//! in an async stack.
+//!
+//! The CLI is still used for the args-analysis subprocess and for `preview`'s
+//! long-running server container (see [`DockerBuilder::for_analyzed_command`]
+//! and `PreviewCommand` in `main.rs`), which need stdout/stderr captured
+//! separately or a detached spawn that this client doesn't support. Runs in
+//! remote (named-volume) mode also go entirely through the CLI, including
+//! the volume staging and the `cp` of outputs back to the host.
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+};
+
+use crate::{atry, docker::Engine, errors::Result};
+use anyhow::{anyhow, bail, ensure};
+
+/// Default location of the daemon's Unix socket.
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// The API version we pin requests to. This is old enough to be widely
+/// supported while new enough for everything we use.
+const API_VERSION: &str = "v1.41";
+
+/// A duplex byte stream to the daemon. Currently always a Unix socket; see
+/// [`Target`] for why we don't support TCP.
+trait Stream: Read + Write {}
+impl Stream for UnixStream {}
+
+/// Knows how to open connections to the configured daemon.
+struct Transport {
+    /// The engine we're driving, so that diagnostics can name it instead of
+    /// assuming Docker.
+    engine: Engine,
+    target: Target,
+    /// Value to send in the HTTP `Host` header.
+    host: String,
+}
+
+enum Target {
+    Unix(String),
+    // There is no `Tcp` variant: the daemons that listen on `tcp://`
+    // endpoints (cloud hosts, Docker Desktop's VM) almost always require
+    // TLS, which this hand-rolled client doesn't implement. We reject such
+    // `DOCKER_HOST` values up front in `Transport::resolve` instead of
+    // pretending to support them.
+}
+
+impl Transport {
+    /// Resolve the daemon location from the environment.
+    fn resolve(engine: Engine) -> Result<Transport> {
+        match env::var("DOCKER_HOST") {
+            Ok(h) if h.starts_with("unix://") => Ok(Transport {
+                engine,
+                target: Target::Unix(h["unix://".len()..].to_owned()),
+                host: "localhost".to_owned(),
+            }),
+            Ok(h) if h.starts_with("tcp://") || h.starts_with("http://") => {
+                // We speak plain HTTP over this socket, and real `tcp://`
+                // daemons (cloud hosts, Docker Desktop's VM) almost always
+                // require TLS. Rather than silently attempt a handshake that
+                // will never succeed against such a daemon, tell the user
+                // up front that we can't talk to it.
+                bail!(
+                    "cannot use DOCKER_HOST `{}`: only unix sockets are supported over the {} API",
+                    h,
+                    engine
+                );
+            }
+            Ok(h) => bail!("unsupported DOCKER_HOST value `{}`", h),
+            Err(_) => Ok(Transport {
+                engine,
+                target: Target::Unix(DEFAULT_SOCKET.to_owned()),
+                host: "localhost".to_owned(),
+            }),
+        }
+    }
+
+    fn connect(&self) -> Result<Box<dyn Stream>> {
+        match &self.target {
+            Target::Unix(path) => {
+                let s = atry!(
+                    UnixStream::connect(path);
+                    ["failed to connect to the {} daemon at `{}`", self.engine, path]
+                );
+                Ok(Box::new(s))
+            }
+        }
+    }
+
+    /// Send a request and return the parsed status line plus a reader
+    /// positioned at the start of the (possibly chunked) response body.
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Response> {
+        let mut stream = self.connect()?;
+
+        let payload = body.map(|v| v.to_string());
+        let mut head = format!(
+            "{method} /{API_VERSION}{path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n",
+            method = method,
+            path = path,
+            host = self.host,
+        );
+
+        if let Some(p) = &payload {
+            head.push_str(&format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n",
+                p.len()
+            ));
+        }
+
+        head.push_str("\r\n");
+
+        atry!(
+            stream.write_all(head.as_bytes());
+            ["failed to send request to the {} daemon", self.engine]
+        );
+
+        if let Some(p) = &payload {
+            atry!(
+                stream.write_all(p.as_bytes());
+                ["failed to send request body to the {} daemon", self.engine]
+            );
+        }
+
+        Response::read(BufReader::new(stream), self.engine)
+    }
+}
+
+/// A decoded HTTP response, with its body left unread for streaming.
+struct Response {
+    /// The engine that served this response, for diagnostics.
+    engine: Engine,
+    status: u16,
+    reader: BufReader<Box<dyn Stream>>,
+    chunked: bool,
+}
+
+impl Response {
+    fn read(mut reader: BufReader<Box<dyn Stream>>, engine: Engine) -> Result<Response> {
+        let mut line = String::new();
+        atry!(
+            reader.read_line(&mut line);
+            ["failed to read the {} daemon's response", engine]
+        );
+
+        // e.g. "HTTP/1.1 201 Created"
+        let status = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok());
+        let status = status.ok_or_else(|| anyhow!("malformed response status line: {:?}", line))?;
+
+        let mut chunked = false;
+
+        loop {
+            let mut header = String::new();
+            atry!(
+                reader.read_line(&mut header);
+                ["failed to read the {} daemon's response headers", engine]
+            );
+
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+
+            if header.eq_ignore_ascii_case("Transfer-Encoding: chunked") {
+                chunked = true;
+            }
+        }
+
+        Ok(Response {
+            engine,
+            status,
+            reader,
+            chunked,
+        })
+    }
+
+    /// Read the full body into a string, decoding chunked transfers.
+    fn text(mut self) -> Result<String> {
+        let mut out = String::new();
+
+        if self.chunked {
+            loop {
+                let mut size_line = String::new();
+                atry!(
+                    self.reader.read_line(&mut size_line);
+                    ["failed to read a response chunk header"]
+                );
+
+                let size = usize::from_str_radix(size_line.trim(), 16).unwrap_or(0);
+                if size == 0 {
+                    break;
+                }
+
+                let mut buf = vec![0u8; size];
+                atry!(
+                    self.reader.read_exact(&mut buf);
+                    ["failed to read a response chunk"]
+                );
+                out.push_str(&String::from_utf8_lossy(&buf));
+
+                // Consume the trailing CRLF after the chunk.
+                let mut crlf = String::new();
+                let _ = self.reader.read_line(&mut crlf);
+            }
+        } else {
+            atry!(
+                self.reader.read_to_string(&mut out);
+                ["failed to read the response body"]
+            );
+        }
+
+        Ok(out)
+    }
+
+    /// Turn this response into a plain byte stream, transparently decoding
+    /// the chunked transfer-encoding used by streaming endpoints like
+    /// `/images/create`. Unlike [`Response::text`], this lets a caller
+    /// process the body incrementally as it arrives instead of waiting for
+    /// it to finish.
+    fn into_body_reader(self) -> BodyReader {
+        BodyReader {
+            reader: self.reader,
+            chunked: self.chunked,
+            chunk_remaining: 0,
+        }
+    }
+}
+
+/// A [`Read`] adapter over a response body that de-chunks it on the fly.
+struct BodyReader {
+    reader: BufReader<Box<dyn Stream>>,
+    chunked: bool,
+    /// Bytes left to read from the current chunk (chunked mode only).
+    chunk_remaining: usize,
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.chunked {
+            return self.reader.read(buf);
+        }
+
+        if self.chunk_remaining == 0 {
+            let mut size_line = String::new();
+            self.reader.read_line(&mut size_line)?;
+            let size = usize::from_str_radix(size_line.trim(), 16)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            if size == 0 {
+                return Ok(0);
+            }
+
+            self.chunk_remaining = size;
+        }
+
+        let want = buf.len().min(self.chunk_remaining);
+        let n = self.reader.read(&mut buf[..want])?;
+        self.chunk_remaining -= n;
+
+        if self.chunk_remaining == 0 {
+            // Consume the trailing CRLF after the chunk.
+            let mut crlf = [0u8; 2];
+            self.reader.read_exact(&mut crlf)?;
+        }
+
+        Ok(n)
+    }
+}
+
+/// Create a container from the given configuration, returning its id.
+pub fn create_container(engine: Engine, name: &str, config: &Value) -> Result<String> {
+    let transport = Transport::resolve(engine)?;
+    let resp = transport.request(
+        "POST",
+        &format!("/containers/create?name={}", name),
+        Some(config),
+    )?;
+
+    let status = resp.status;
+    let text = resp.text()?;
+
+    ensure!(
+        status == 201,
+        "the {} daemon rejected the container creation (HTTP {}): {}",
+        engine,
+        status,
+        text.trim()
+    );
+
+    #[derive(Deserialize)]
+    struct Created {
+        #[serde(rename = "Id")]
+        id: String,
+    }
+
+    let created: Created = atry!(
+        serde_json::from_str(&text);
+        ["failed to parse the container creation response"]
+    );
+
+    Ok(created.id)
+}
+
+/// Run a previously-created container to completion.
+///
+/// This attaches to the container's (raw TTY) output, starts it, relays
+/// everything it prints to our terminal, and then waits for and returns its
+/// exit code. The container is removed afterward — even if we bail out early
+/// or panic partway through, via [`ContainerGuard`].
+pub fn run_container(engine: Engine, id: &str) -> Result<i32> {
+    let transport = Transport::resolve(engine)?;
+    let _guard = ContainerGuard {
+        transport: &transport,
+        id,
+    };
+
+    // Attach first, so that we don't miss any early output, then start.
+    let attach = transport.request(
+        "POST",
+        &format!(
+            "/containers/{}/attach?stream=1&stdout=1&stderr=1&logs=1",
+            id
+        ),
+        None,
+    )?;
+
+    ensure!(
+        attach.status == 200 || attach.status == 101,
+        "the {} daemon refused to attach to the container (HTTP {})",
+        engine,
+        attach.status
+    );
+
+    let start = transport.request("POST", &format!("/containers/{}/start", id), None)?;
+    ensure!(
+        start.status == 204 || start.status == 304,
+        "the {} daemon failed to start the container (HTTP {})",
+        engine,
+        start.status
+    );
+
+    // Relay the container's output until the stream closes (i.e. it exits).
+    relay_stream(attach)?;
+
+    wait_container(&transport, id)
+}
+
+/// Ensures a run container is always removed, no matter where in
+/// `run_container` we exit — a successful return, an early `?`/`ensure!`
+/// failure, or a panic. Mirrors the [`RemoteGuard`](crate::docker::RemoteGuard)
+/// drop discipline used for remote (named-volume) runs.
+struct ContainerGuard<'a> {
+    transport: &'a Transport,
+    id: &'a str,
+}
+
+impl Drop for ContainerGuard<'_> {
+    fn drop(&mut self) {
+        remove_container(self.transport, self.id);
+    }
+}
+
+/// Copy the attached output stream to our stdout until it closes.
+///
+/// Because we create the container with a TTY, the stream is a single raw byte
+/// flow rather than the multiplexed stdout/stderr framing used for non-TTY
+/// containers, so we can relay it verbatim.
+fn relay_stream(mut resp: Response) -> Result<()> {
+    let engine = resp.engine;
+    let mut buf = [0u8; 8192];
+    let mut stdout = std::io::stdout();
+
+    loop {
+        let n = atry!(
+            resp.reader.read(&mut buf);
+            ["failed to read container output from the {} daemon", engine]
+        );
+
+        if n == 0 {
+            break;
+        }
+
+        atry!(
+            stdout.write_all(&buf[..n]);
+            ["failed to relay container output"]
+        );
+        let _ = stdout.flush();
+    }
+
+    Ok(())
+}
+
+/// Block until the container exits, returning its status code.
+fn wait_container(transport: &Transport, id: &str) -> Result<i32> {
+    let resp = transport.request("POST", &format!("/containers/{}/wait", id), None)?;
+    let status = resp.status;
+    let text = resp.text()?;
+
+    ensure!(
+        status == 200,
+        "the {} daemon failed to wait on the container (HTTP {}): {}",
+        transport.engine,
+        status,
+        text.trim()
+    );
+
+    #[derive(Deserialize)]
+    struct Wait {
+        #[serde(rename = "StatusCode")]
+        status_code: i32,
+    }
+
+    let wait: Wait = atry!(
+        serde_json::from_str(&text);
+        ["failed to parse the container wait response"]
+    );
+
+    Ok(wait.status_code)
+}
+
+/// Remove the container, ignoring any failure (best-effort cleanup).
+fn remove_container(transport: &Transport, id: &str) {
+    if let Ok(resp) = transport.request("DELETE", &format!("/containers/{}?force=1", id), None) {
+        let _ = resp.text();
+    }
+}
+
+/// Pull an image, relaying the daemon's progress stream to stdout.
+pub fn pull_image(engine: Engine, image: &str) -> Result<()> {
+    let (name, tag) = match image.split_once('@') {
+        // A digest is passed to the API in place of a tag.
+        Some((n, d)) => (n, d),
+        None => match image.rsplit_once(':') {
+            // A registry port is not a tag; treat anything with a slash
+            // after the colon as part of the name.
+            Some((n, t)) if !t.contains('/') => (n, t),
+            _ => (image, "latest"),
+        },
+    };
+
+    let transport = Transport::resolve(engine)?;
+    let resp = transport.request(
+        "POST",
+        &format!("/images/create?fromImage={}&tag={}", name, tag),
+        None,
+    )?;
+
+    ensure!(
+        resp.status == 200,
+        "the {} daemon refused to pull `{}` (HTTP {})",
+        engine,
+        image,
+        resp.status
+    );
+
+    // The body is a stream of JSON progress objects. Parse and print them as
+    // they arrive rather than buffering the whole (possibly multi-hundred-MB
+    // pull's worth of) response before showing anything.
+    #[derive(Deserialize)]
+    struct Progress {
+        status: Option<String>,
+        progress: Option<String>,
+        error: Option<String>,
+    }
+
+    let reader = resp.into_body_reader();
+    let mut stdout = std::io::stdout();
+
+    for obj in serde_json::Deserializer::from_reader(reader).into_iter::<Progress>() {
+        // A hard parse error doesn't advance the deserializer's read
+        // position, so retrying by `continue`-ing would just re-parse the
+        // same bytes forever; give up instead.
+        let obj = atry!(obj; ["failed to parse the {} daemon's pull progress", engine]);
+
+        if let Some(err) = obj.error {
+            bail!("the {} daemon reported a pull error: {}", engine, err);
+        }
+
+        if let Some(status) = obj.status {
+            match obj.progress {
+                Some(p) => println!("{} {}", status, p),
+                None => println!("{}", status),
+            }
+            let _ = stdout.flush();
+        }
+    }
+
+    Ok(())
+}
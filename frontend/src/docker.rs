@@ -5,28 +5,151 @@
 
 use anyhow::ensure;
 use serde::Deserialize;
+use serde_json::{json, Map, Value};
 use std::{
     collections::HashMap,
+    env,
     ffi::{OsStr, OsString},
+    fmt,
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Command,
+    sync::OnceLock,
 };
 
 use crate::{a_ok_or, atry, errors::Result};
 
-const DOCKER_COMMAND: &str = "docker";
 const DEFAULT_IMAGE_NAME: &str = "aasworldwidetelescope/aligner:latest";
 const DEFAULT_INNER_COMMAND: &str = "wwt-aligner-agent";
 const SUPPORTED_ARGS_PROTOCOL_VERSION: usize = 1;
 
+/// Environment variable forcing the choice of container engine.
+const ENGINE_ENVVAR: &str = "WWT_CONTAINER_ENGINE";
+
+/// Environment variable disabling container confinement, for debugging.
+const UNCONFINED_ENVVAR: &str = "WWT_ALIGNER_UNCONFINED";
+
+/// Environment variable overriding the image reference.
+const IMAGE_ENVVAR: &str = "WWT_ALIGNER_IMAGE";
+
+/// Environment variable enabling verbose reporting.
+const VERBOSE_ENVVAR: &str = "WWT_ALIGNER_VERBOSE";
+
+/// Process-global overrides set from the command line by [`configure`].
+static IMAGE_OVERRIDE: OnceLock<String> = OnceLock::new();
+static VERBOSE_OVERRIDE: OnceLock<bool> = OnceLock::new();
+
+/// The bundled seccomp profile applied to container runs by default.
+const SECCOMP_PROFILE: &str = include_str!("seccomp.json");
+
+/// A container engine used to drive the aligner backend.
+///
+/// We support the two common Docker-compatible CLIs. They accept almost the
+/// same command lines, but have a few behavioral differences that we need to
+/// paper over (most notably, Podman normally runs rootless, so the
+/// `HOST_UID`/`HOST_GID` remapping that we do for Docker is both unnecessary
+/// and harmful).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Engine {
+    Docker,
+    Podman,
+}
+
+impl Engine {
+    /// The name of this engine's executable, as searched for on `PATH`.
+    fn program(self) -> &'static str {
+        match self {
+            Engine::Docker => "docker",
+            Engine::Podman => "podman",
+        }
+    }
+
+    /// Start building a command line that invokes this engine.
+    fn command(self) -> Command {
+        Command::new(self.program())
+    }
+
+    /// Detect the container engine to use.
+    ///
+    /// If the `WWT_CONTAINER_ENGINE` environment variable is set, it is honored
+    /// (and an unrecognized value is an error). Otherwise we probe `PATH` for
+    /// `docker` and then `podman`, in that order. The result is computed once
+    /// and cached for the remainder of the process, since it cannot change
+    /// underneath us.
+    pub fn get() -> Result<Engine> {
+        static CACHE: OnceLock<Option<Engine>> = OnceLock::new();
+
+        if let Some(os) = env::var_os(ENGINE_ENVVAR) {
+            // An explicit override bypasses (and does not populate) the cache,
+            // so that it can be reported precisely.
+            return match os.to_str() {
+                Some("docker") => Ok(Engine::Docker),
+                Some("podman") => Ok(Engine::Podman),
+                _ => Err(anyhow::anyhow!(
+                    "unrecognized value for the {} environment variable: expected `docker` or `podman`",
+                    ENGINE_ENVVAR
+                )),
+            };
+        }
+
+        let engine = *CACHE.get_or_init(Engine::probe);
+
+        a_ok_or!(
+            engine;
+            ["no container engine found: neither `docker` nor `podman` is available on your PATH \
+              (set the {} environment variable to choose one explicitly)", ENGINE_ENVVAR]
+        )
+    }
+
+    /// Probe `PATH` for a usable engine, preferring `docker`.
+    fn probe() -> Option<Engine> {
+        for engine in [Engine::Docker, Engine::Podman] {
+            if program_on_path(engine.program()) {
+                return Some(engine);
+            }
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.program())
+    }
+}
+
+/// Test whether the named program is resolvable on the current `PATH`.
+fn program_on_path(program: &str) -> bool {
+    let path = match env::var_os("PATH") {
+        Some(p) => p,
+        None => return false,
+    };
+
+    env::split_paths(&path).any(|dir| {
+        // An empty entry means the current directory, which we never want to
+        // treat as holding the engine binary.
+        !dir.as_os_str().is_empty() && dir.join(program).is_file()
+    })
+}
+
 /// Helper for constructing Docker command lines.
 #[derive(Debug)]
 pub struct DockerBuilder {
+    engine: Engine,
     image_name: String,
     volumes: Vec<DockerVolume>,
     ports: Vec<DockerPort>,
     inner_args: Vec<OsString>,
+
+    /// Whether to run against a remote daemon that does not share our
+    /// filesystem. In this mode we cannot bind-mount host directories and must
+    /// instead stage files into named volumes (see [`DockerBuilder::prepare`]).
+    remote: bool,
+
+    /// Path to the temporary seccomp profile written for this run, if any. It
+    /// is removed when the builder is dropped.
+    seccomp_path: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -34,6 +157,26 @@ struct DockerVolume {
     host_path: PathBuf,
     container_path: PathBuf,
     read_write: bool,
+
+    /// Name of the ephemeral named volume used to carry this directory's files
+    /// in remote mode. Unused when bind-mounting.
+    name: String,
+
+    /// The individual files routed through this directory, recorded so that we
+    /// can stage inputs in and copy outputs back out in remote mode.
+    files: Vec<DockerVolumeFile>,
+}
+
+#[derive(Debug)]
+struct DockerVolumeFile {
+    /// This file's path as seen from our own filesystem — valid for `docker
+    /// cp`, which we always run ourselves, but not necessarily for the
+    /// daemon (see the docker-in-docker rewrite in
+    /// [`DockerBuilder::for_analyzed_command`]).
+    local_path: PathBuf,
+    container_path: String,
+    path_pre_exists: bool,
+    path_created: bool,
 }
 
 #[derive(Debug)]
@@ -43,42 +186,54 @@ struct DockerPort {
     container_port: u16,
 }
 
-impl Default for DockerBuilder {
-    fn default() -> Self {
+impl DockerBuilder {
+    /// Create an empty builder that will drive the given container engine.
+    fn new(engine: Engine) -> Self {
         DockerBuilder {
+            engine,
             image_name: DEFAULT_IMAGE_NAME.to_owned(),
             volumes: Default::default(),
             ports: Default::default(),
             inner_args: Default::default(),
+            remote: remote_requested(),
+            seccomp_path: None,
         }
     }
-}
 
-impl DockerBuilder {
     pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
         self.inner_args.push(arg.as_ref().to_owned());
         self
     }
 
+    /// This still shells out to the `docker`/`podman` CLI rather than the
+    /// HTTP API that [`run_via_api`](Self::run_via_api) uses for the actual
+    /// run: we need separately-captured stdout/stderr (stdout carries the
+    /// args-protocol JSON; stderr is passed through verbatim) and
+    /// `Command::output` already gives us exactly that, whereas the API's
+    /// attach endpoint only exposes a single combined TTY stream. The CLI is
+    /// still required for this step.
     pub fn for_analyzed_command(args: &[OsString]) -> Result<Option<Self>> {
-        let mut analyze_cmd = Command::new(DOCKER_COMMAND);
+        let engine = Engine::get()?;
+        let image = resolve_image()?;
+
+        let mut analyze_cmd = engine.command();
         analyze_cmd
             .arg("run")
             .arg("--rm")
-            .arg(DEFAULT_IMAGE_NAME)
+            .arg(&image)
             .arg(DEFAULT_INNER_COMMAND)
             .arg("--x-analyze-args-mode")
             .args(args);
 
         let output = atry!(
             analyze_cmd.output();
-            ["failed to launch the Docker command: {:?}", analyze_cmd]
+            ["failed to launch the {} command: {:?}", engine, analyze_cmd]
         );
 
         // If there was any stderr output, get it out there.
         atry!(
             io::stderr().write_all(&output.stderr);
-            ["failed to transfer Docker error output to stderr"]
+            ["failed to transfer {} error output to stderr", engine]
         );
 
         if let Some(0) = output.status.code() {
@@ -96,18 +251,18 @@ impl DockerBuilder {
                 // Otherwise, something went unexpectedly wrong.
 
                 eprintln!(
-                    "error: the Docker command signaled failure (error code {})",
-                    c
+                    "error: the {} command signaled failure (error code {})",
+                    engine, c
                 );
             } else {
-                eprintln!("error: the Docker command exited unexpectedly");
+                eprintln!("error: the {} command exited unexpectedly", engine);
             }
 
             if !output.stdout.is_empty() {
                 eprintln!("error: the command's primary output was:\n");
                 atry!(
                     io::stderr().write_all(&output.stdout);
-                    ["failed to transfer Docker stdout output"]
+                    ["failed to transfer {} stdout output", engine]
                 );
             }
         }
@@ -133,7 +288,8 @@ impl DockerBuilder {
 
         // Finally, create our actual Docker command from the analyzed arguments.
 
-        let mut builder = DockerBuilder::default();
+        let mut builder = DockerBuilder::new(engine);
+        builder.image_name = image;
         let mut volumes = HashMap::new();
         let mut arg = String::new();
         let mut args = Vec::new();
@@ -157,7 +313,7 @@ impl DockerBuilder {
                 // canonicalization step will fail if the path does not exist,
                 // which will be the case for output files.
 
-                let host_path = if piece.path_pre_exists {
+                let local_path = if piece.path_pre_exists {
                     // This path should exist. We can (must) use std::fs::canonicalize
                     // (in case the final path component is a symlink).
                     atry!(
@@ -186,6 +342,20 @@ impl DockerBuilder {
                     canon
                 };
 
+                // If we are ourselves running inside a container that shares a
+                // daemon with the host (docker-in-docker / sibling containers),
+                // the canonical path above is meaningful only inside *our*
+                // filesystem, not the daemon's. Rewrite it to the corresponding
+                // real-host path before we use it to set up a bind mount. Note
+                // that this rewritten path is only valid for the daemon; any
+                // command we execute ourselves (like `docker cp`) still needs
+                // to see `local_path`, since it runs in our own filesystem.
+
+                let host_path = atry!(
+                    host_path_for(local_path.clone());
+                    ["failed to map `{}` to a host path for docker-in-docker", &piece.text]
+                );
+
                 // OK, now that we have the canonical host path, we can determine its
                 // containing directory.
 
@@ -225,16 +395,33 @@ impl DockerBuilder {
                 // Ensure that we will have a Docker volume mount so that this
                 // file can be accessed inside the container.
 
-                let vol = volumes.entry(host_dir.clone()).or_insert(DockerVolume {
-                    host_path: host_dir,
-                    container_path: container_dir.into(),
-                    read_write: false,
+                let vol = volumes.entry(host_dir.clone()).or_insert_with(|| {
+                    let name = format!(
+                        "wwt-aligner-{}-{}",
+                        std::process::id(),
+                        container_dir.trim_start_matches("/volumes/")
+                    );
+
+                    DockerVolume {
+                        host_path: host_dir,
+                        container_path: container_dir.into(),
+                        read_write: false,
+                        name,
+                        files: Vec::new(),
+                    }
                 });
 
                 if piece.path_created {
                     vol.read_write = true;
                 }
 
+                vol.files.push(DockerVolumeFile {
+                    local_path: local_path.clone(),
+                    container_path: container_path.clone(),
+                    path_pre_exists: piece.path_pre_exists,
+                    path_created: piece.path_created,
+                });
+
                 // Finally, the ultimate "processed" value of this argument to propagate
                 // into the docker container:
                 container_path
@@ -274,23 +461,135 @@ impl DockerBuilder {
         Ok(Some(builder))
     }
 
-    pub fn into_command(mut self) -> Command {
-        let mut cmd = Command::new(DOCKER_COMMAND);
+    /// Whether this command will run against a remote daemon.
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
 
-        cmd.arg("run").arg("--rm").arg("-it");
+    /// The container engine driving this command, for callers that need to
+    /// surface it (e.g. in diagnostics).
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
 
-        for vol in self.volumes.drain(..) {
-            cmd.arg("-v");
-
-            let mut vstr = OsString::from(vol.host_path);
-            vstr.push(":");
-            vstr.push(vol.container_path);
-            vstr.push(":");
-            vstr.push(if vol.read_write { "rw" } else { "ro" });
-            cmd.arg(vstr);
+    /// Prepare any remote resources needed before the container runs.
+    ///
+    /// In local (bind-mount) mode this is a no-op and returns `None`. In remote
+    /// mode it creates one named volume per mounted directory and stages every
+    /// pre-existing input file into it via a throwaway stub container, then
+    /// returns a [`RemoteGuard`] that will tear the resources down on drop.
+    pub fn prepare(&self) -> Result<Option<RemoteGuard>> {
+        if !self.remote {
+            return Ok(None);
+        }
+
+        let mut guard = RemoteGuard {
+            engine: self.engine,
+            volumes: Vec::new(),
+            stub_containers: Vec::new(),
+            container: None,
+            outputs: Vec::new(),
+        };
+
+        for vol in &self.volumes {
+            atry!(
+                run_checked(self.engine.command().arg("volume").arg("create").arg(&vol.name));
+                ["failed to create the data volume `{}`", vol.name]
+            );
+            guard.volumes.push(vol.name.clone());
+
+            let inputs: Vec<&DockerVolumeFile> =
+                vol.files.iter().filter(|f| f.path_pre_exists).collect();
+
+            if !inputs.is_empty() {
+                // Stage the inputs by copying them into a stub container that
+                // has the volume mounted; the stub never runs.
+                let mount = format!("{}:{}", vol.name, vol.container_path.display());
+                let stub = atry!(
+                    capture_line(
+                        self.engine
+                            .command()
+                            .arg("create")
+                            .arg("-v")
+                            .arg(&mount)
+                            .arg(&self.image_name)
+                            .arg("true")
+                    );
+                    ["failed to create a staging container on volume `{}`", vol.name]
+                );
+
+                // Record the stub with the guard *before* copying anything
+                // into it, so that a failed `cp` or an early exit still gets
+                // it cleaned up.
+                guard.stub_containers.push(stub.clone());
+
+                for file in inputs {
+                    let dest = format!("{}:{}", stub, file.container_path);
+                    atry!(
+                        run_checked(
+                            self.engine
+                                .command()
+                                .arg("cp")
+                                .arg(&file.local_path)
+                                .arg(&dest)
+                        );
+                        ["failed to stage input file `{}` into the data volume", file.local_path.display()]
+                    );
+                }
+            }
+
+            for file in vol.files.iter().filter(|f| f.path_created) {
+                guard
+                    .outputs
+                    .push((file.local_path.clone(), file.container_path.clone()));
+            }
+        }
+
+        Ok(Some(guard))
+    }
+
+    /// Build the container `run` command.
+    ///
+    /// In remote mode, `guard` must be the value returned by [`prepare`]; the
+    /// run container is given an explicit name (recorded in the guard so that
+    /// outputs can be copied out and the container removed afterward) and the
+    /// named volumes are mounted in place of bind mounts.
+    ///
+    /// [`prepare`]: DockerBuilder::prepare
+    pub fn into_command(&mut self, guard: Option<&mut RemoteGuard>) -> Result<Command> {
+        let mut cmd = self.engine.command();
+
+        cmd.arg("run").arg("-it");
+
+        if self.remote {
+            // We can't rely on `--rm`, because we need the container to stick
+            // around long enough to copy the outputs out of it.
+            let name = format!("wwt-aligner-run-{}", std::process::id());
+            cmd.arg("--name").arg(&name);
+            if let Some(g) = guard {
+                g.container = Some(name);
+            }
+
+            for vol in &self.volumes {
+                cmd.arg("-v");
+                cmd.arg(format!("{}:{}", vol.name, vol.container_path.display()));
+            }
+        } else {
+            cmd.arg("--rm");
+
+            for vol in &self.volumes {
+                cmd.arg("-v");
+
+                let mut vstr = OsString::from(&vol.host_path);
+                vstr.push(":");
+                vstr.push(&vol.container_path);
+                vstr.push(":");
+                vstr.push(if vol.read_write { "rw" } else { "ro" });
+                cmd.arg(vstr);
+            }
         }
 
-        for port in self.ports.drain(..) {
+        for port in &self.ports {
             cmd.arg("-p");
             cmd.arg(format!(
                 "{}:{}:{}",
@@ -298,27 +597,459 @@ impl DockerBuilder {
             ));
         }
 
-        let uid = nix::unistd::geteuid();
-        cmd.arg("-e").arg(format!("HOST_UID={}", uid));
+        match self.engine {
+            Engine::Docker => {
+                // The Docker daemon runs as root and creates files owned by
+                // root, so the agent re-drops privileges to our uid/gid.
+                let uid = nix::unistd::geteuid();
+                cmd.arg("-e").arg(format!("HOST_UID={}", uid));
+
+                let gid = nix::unistd::getegid();
+                cmd.arg("-e").arg(format!("HOST_GID={}", gid));
+            }
+
+            Engine::Podman => {
+                // Rootless Podman already maps the container root to our host
+                // uid, so injecting HOST_UID/HOST_GID would make the agent
+                // chown files to the wrong owner. Instead, ask Podman to keep
+                // our uid inside the user namespace so created files land with
+                // the right ownership without any help from the agent.
+                cmd.arg("--userns=keep-id");
+            }
+        }
+
+        // Confine the container, since it handles untrusted image files. This
+        // can be turned off for debugging via the escape hatch below.
 
-        let gid = nix::unistd::getegid();
-        cmd.arg("-e").arg(format!("HOST_GID={}", gid));
+        if unconfined_requested() {
+            eprintln!(
+                "warning: running the container unconfined ({} is set)",
+                UNCONFINED_ENVVAR
+            );
+        } else {
+            cmd.arg("--cap-drop=ALL");
+            cmd.arg("--security-opt").arg("no-new-privileges");
+
+            let path = self.write_seccomp_profile()?;
+            let mut opt = OsString::from("seccomp=");
+            opt.push(&path);
+            cmd.arg("--security-opt").arg(opt);
+            self.seccomp_path = Some(path);
+        }
 
-        cmd.arg(self.image_name);
+        cmd.arg(&self.image_name);
 
-        for arg in self.inner_args.drain(..) {
+        for arg in &self.inner_args {
             cmd.arg(arg);
         }
 
+        Ok(cmd)
+    }
+
+    /// Run this command through the Docker Engine HTTP API, returning the
+    /// backend's exit code.
+    ///
+    /// This is the counterpart to driving `into_command().status()`, but it
+    /// talks to the daemon directly (see the [`api`](crate::api) module) rather
+    /// than shelling out. The run itself spawns no CLI process, but a builder
+    /// only comes from [`for_analyzed_command`](Self::for_analyzed_command),
+    /// which runs the `docker`/`podman` CLI, so that binary must still be
+    /// installed.
+    pub fn run_via_api(&self) -> Result<i32> {
+        let name = format!("wwt-aligner-run-{}", std::process::id());
+        let config = self.create_config();
+        let id = crate::api::create_container(self.engine, &name, &config)?;
+        crate::api::run_container(self.engine, &id)
+    }
+
+    /// Build the container-create configuration for the Docker Engine API,
+    /// expressing the same image, volumes, ports, environment, and confinement
+    /// that [`into_command`](DockerBuilder::into_command) would pass as CLI
+    /// flags.
+    fn create_config(&self) -> Value {
+        let cmd: Vec<Value> = self
+            .inner_args
+            .iter()
+            .map(|a| Value::from(a.to_string_lossy().into_owned()))
+            .collect();
+
+        let mut binds: Vec<Value> = Vec::new();
+        for vol in &self.volumes {
+            if self.remote {
+                binds.push(Value::from(format!(
+                    "{}:{}",
+                    vol.name,
+                    vol.container_path.display()
+                )));
+            } else {
+                binds.push(Value::from(format!(
+                    "{}:{}:{}",
+                    vol.host_path.display(),
+                    vol.container_path.display(),
+                    if vol.read_write { "rw" } else { "ro" }
+                )));
+            }
+        }
+
+        let mut exposed = Map::new();
+        let mut port_bindings = Map::new();
+        for port in &self.ports {
+            let key = format!("{}/tcp", port.container_port);
+            exposed.insert(key.clone(), json!({}));
+            port_bindings.insert(
+                key,
+                json!([{ "HostIp": port.host_ip, "HostPort": port.host_port.to_string() }]),
+            );
+        }
+
+        let mut env: Vec<Value> = Vec::new();
+        let mut host_config = Map::new();
+        host_config.insert("Binds".to_owned(), Value::from(binds));
+        host_config.insert("PortBindings".to_owned(), Value::Object(port_bindings));
+        host_config.insert("AutoRemove".to_owned(), Value::from(false));
+
+        match self.engine {
+            Engine::Docker => {
+                env.push(Value::from(format!("HOST_UID={}", nix::unistd::geteuid())));
+                env.push(Value::from(format!("HOST_GID={}", nix::unistd::getegid())));
+            }
+            Engine::Podman => {
+                host_config.insert("UsernsMode".to_owned(), Value::from("keep-id"));
+            }
+        }
+
+        if !unconfined_requested() {
+            host_config.insert("CapDrop".to_owned(), json!(["ALL"]));
+            host_config.insert(
+                "SecurityOpt".to_owned(),
+                json!(["no-new-privileges", format!("seccomp={}", SECCOMP_PROFILE)]),
+            );
+        }
+
+        json!({
+            "Image": self.image_name,
+            "Cmd": cmd,
+            "Tty": true,
+            "AttachStdout": true,
+            "AttachStderr": true,
+            "Env": env,
+            "ExposedPorts": Value::Object(exposed),
+            "HostConfig": Value::Object(host_config),
+        })
+    }
+
+    /// Write the bundled seccomp profile to a temporary file and return its
+    /// path. The file is removed when the builder is dropped.
+    fn write_seccomp_profile(&self) -> Result<PathBuf> {
+        let mut path = env::temp_dir();
+        path.push(format!("wwt-aligner-seccomp-{}.json", std::process::id()));
+
+        atry!(
+            std::fs::write(&path, SECCOMP_PROFILE);
+            ["failed to write the seccomp profile to `{}`", path.display()]
+        );
+
+        Ok(path)
+    }
+}
+
+impl Drop for DockerBuilder {
+    fn drop(&mut self) {
+        if let Some(path) = self.seccomp_path.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Tracks the ephemeral resources created for a remote run — named volumes and
+/// the (non-`--rm`) run container — so that they are always torn down, even if
+/// we panic or bail out early.
+#[derive(Debug)]
+pub struct RemoteGuard {
+    engine: Engine,
+    volumes: Vec<String>,
+    /// Throwaway containers created to stage input files into the named
+    /// volumes (see [`DockerBuilder::prepare`]). Recorded as soon as they are
+    /// created, before we start copying files into them, so that a failed
+    /// `cp` or an early exit still gets them cleaned up.
+    stub_containers: Vec<String>,
+    container: Option<String>,
+    outputs: Vec<(PathBuf, String)>,
+}
+
+impl RemoteGuard {
+    /// Copy every created output file out of the finished run container and
+    /// onto the host. Call this only after a successful run, before the guard
+    /// is dropped (which removes the container).
+    pub fn collect_outputs(&self) -> Result<()> {
+        let container = a_ok_or!(
+            self.container.as_ref();
+            ["internal error: no run container was recorded for output collection"]
+        );
+
+        for (local_path, container_path) in &self.outputs {
+            let src = format!("{}:{}", container, container_path);
+            atry!(
+                run_checked(self.engine.command().arg("cp").arg(&src).arg(local_path));
+                ["failed to copy output file `{}` out of the container", local_path.display()]
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for RemoteGuard {
+    fn drop(&mut self) {
+        if let Some(container) = self.container.take() {
+            let _ = self.engine.command().arg("rm").arg("-f").arg(&container).output();
+        }
+
+        for stub in self.stub_containers.drain(..) {
+            let _ = self.engine.command().arg("rm").arg("-f").arg(&stub).output();
+        }
+
+        for vol in self.volumes.drain(..) {
+            let _ = self
+                .engine
+                .command()
+                .arg("volume")
+                .arg("rm")
+                .arg("-f")
+                .arg(&vol)
+                .output();
+        }
+    }
+}
+
+/// Should we run against a remote daemon rather than bind-mounting?
+///
+/// This is triggered by the presence of a `DOCKER_HOST` setting (the daemon is
+/// then presumed not to share our filesystem) or by an explicit
+/// `WWT_ALIGNER_REMOTE` override.
+fn remote_requested() -> bool {
+    env::var_os("DOCKER_HOST").is_some() || bool_from_envvar("WWT_ALIGNER_REMOTE")
+}
+
+/// Describes where the frontend's in-container filesystem is mounted on the
+/// real host, for docker-in-docker path translation.
+#[derive(Debug)]
+struct HostMapping {
+    container_root: PathBuf,
+    host_root: PathBuf,
+}
+
+/// Return the active docker-in-docker host mapping, if any.
+///
+/// A mapping exists only when we are running inside a container (detected by
+/// the presence of `/.dockerenv`) *and* the caller has pointed us at the host
+/// location of our filesystem via `WWT_HOST_ROOT`. The in-container mount point
+/// that this corresponds to defaults to `/` but can be narrowed with
+/// `WWT_CONTAINER_ROOT` (e.g. when only a working directory is shared). The
+/// result is computed once and cached.
+fn host_mapping() -> Option<&'static HostMapping> {
+    static CACHE: OnceLock<Option<HostMapping>> = OnceLock::new();
+
+    CACHE
+        .get_or_init(|| {
+            if !Path::new("/.dockerenv").exists() {
+                return None;
+            }
+
+            let host_root = env::var_os("WWT_HOST_ROOT")?;
+            let container_root =
+                env::var_os("WWT_CONTAINER_ROOT").unwrap_or_else(|| OsString::from("/"));
+
+            Some(HostMapping {
+                container_root: container_root.into(),
+                host_root: host_root.into(),
+            })
+        })
+        .as_ref()
+}
+
+/// Translate an in-container path into the equivalent path on the real host.
+///
+/// Outside of docker-in-docker, or when no mapping is configured, the path is
+/// returned unchanged.
+fn host_path_for(path: PathBuf) -> Result<PathBuf> {
+    let mapping = match host_mapping() {
+        Some(m) => m,
+        None => return Ok(path),
+    };
+
+    let rel = atry!(
+        path.strip_prefix(&mapping.container_root);
+        ["the path `{}` is not under the configured container root `{}`",
+         path.display(), mapping.container_root.display()]
+    );
+
+    Ok(mapping.host_root.join(rel))
+}
+
+/// Should we skip container confinement (seccomp, capability dropping)?
+///
+/// This is an escape hatch for debugging; it should not be used in production.
+fn unconfined_requested() -> bool {
+    bool_from_envvar(UNCONFINED_ENVVAR)
+}
+
+/// Run a command to completion, turning a nonzero exit into an error.
+fn run_checked(cmd: &mut Command) -> Result<()> {
+    let output = atry!(
+        cmd.output();
+        ["failed to launch the command: {:?}", cmd]
+    );
+
+    atry!(
+        io::stderr().write_all(&output.stderr);
+        ["failed to transfer command error output to stderr"]
+    );
+
+    ensure!(
+        output.status.success(),
+        "the command {:?} signaled failure",
+        cmd
+    );
+
+    Ok(())
+}
+
+/// Run a command and return its first line of standard output, trimmed.
+///
+/// Used for commands like `docker create` that print an identifier we need to
+/// act on.
+fn capture_line(cmd: &mut Command) -> Result<String> {
+    let output = atry!(
+        cmd.output();
+        ["failed to launch the command: {:?}", cmd]
+    );
+
+    atry!(
+        io::stderr().write_all(&output.stderr);
+        ["failed to transfer command error output to stderr"]
+    );
+
+    ensure!(
+        output.status.success(),
+        "the command {:?} signaled failure",
         cmd
+    );
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(text.lines().next().unwrap_or("").trim().to_owned())
+}
+
+/// Pull the aligner image with the given tag, streaming progress from the
+/// Docker Engine API.
+///
+/// The image *name* is resolved through the usual configuration layering; only
+/// its tag is replaced with the requested one.
+pub fn pull_image(tag: &str) -> Result<()> {
+    let engine = Engine::get()?;
+    let image = image_for_tag(&resolve_image()?, tag);
+    crate::api::pull_image(engine, &image)
+}
+
+/// Replace the tag of an image reference, preserving its registry and name.
+fn image_for_tag(image: &str, tag: &str) -> String {
+    let base = image
+        .rsplit_once(':')
+        // A colon before the last `/` belongs to a registry host:port, not a
+        // tag, so leave it alone.
+        .filter(|(_, rest)| !rest.contains('/'))
+        .map(|(base, _)| base)
+        .unwrap_or(image);
+    format!("{}:{}", base, tag)
+}
+
+/// Record the command-line overrides for image selection and verbosity.
+///
+/// Called once at startup; later calls are ignored.
+pub fn configure(image: Option<String>, verbose: bool) {
+    if let Some(image) = image {
+        let _ = IMAGE_OVERRIDE.set(image);
     }
+
+    let _ = VERBOSE_OVERRIDE.set(verbose);
 }
 
-/// Generate a Command that will update the Docker image.
-pub fn update_command() -> Command {
-    let mut cmd = Command::new(DOCKER_COMMAND);
-    cmd.arg("pull").arg(DEFAULT_IMAGE_NAME);
-    cmd
+/// Resolve the image reference to use.
+///
+/// Resolution precedence is, highest first: the `--image` command-line flag,
+/// the `WWT_ALIGNER_IMAGE` environment variable, the `image` key of the user's
+/// config file, and finally the built-in default. The chosen value and its
+/// source are reported in verbose mode.
+fn resolve_image() -> Result<String> {
+    if let Some(image) = IMAGE_OVERRIDE.get() {
+        return Ok(report_image(image.clone(), "the --image flag"));
+    }
+
+    if let Some(os) = env::var_os(IMAGE_ENVVAR) {
+        if !os.is_empty() {
+            let image = os.to_string_lossy().into_owned();
+            return Ok(report_image(image, IMAGE_ENVVAR));
+        }
+    }
+
+    if let Some(image) = image_from_config()? {
+        return Ok(report_image(image, "the config file"));
+    }
+
+    Ok(report_image(DEFAULT_IMAGE_NAME.to_owned(), "the built-in default"))
+}
+
+/// Note the resolved image and its source when running verbosely.
+fn report_image(image: String, source: &str) -> String {
+    if verbose() {
+        eprintln!("verbose: using image `{}` (from {})", image, source);
+    }
+    image
+}
+
+/// Read the `image` key from the user's config file, if it exists.
+fn image_from_config() -> Result<Option<String>> {
+    let dir = match dirs::config_dir() {
+        Some(d) => d,
+        None => return Ok(None),
+    };
+
+    let path = dir.join("wwt-aligner").join("config.toml");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let text = atry!(
+        std::fs::read_to_string(&path);
+        ["failed to read the config file `{}`", path.display()]
+    );
+
+    let config: FileConfig = atry!(
+        toml::from_str(&text);
+        ["failed to parse the config file `{}`", path.display()]
+    );
+
+    Ok(config.image)
+}
+
+/// The subset of the user's config file that we consult.
+#[derive(Debug, Default, Deserialize)]
+struct FileConfig {
+    image: Option<String>,
+}
+
+/// Whether we are reporting verbosely.
+fn verbose() -> bool {
+    VERBOSE_OVERRIDE.get().copied().unwrap_or(false) || bool_from_envvar(VERBOSE_ENVVAR)
+}
+
+/// Interpret an environment variable as a boolean, following the same
+/// lenient conventions as cross's helper of the same name.
+fn bool_from_envvar(var: &str) -> bool {
+    match env::var(var) {
+        Ok(v) => matches!(v.to_lowercase().as_str(), "1" | "true" | "yes" | "on"),
+        Err(_) => false,
+    }
 }
 
 /// The main "args protocol" data payload.
@@ -8,6 +8,7 @@
 use std::{ffi::OsString, path::PathBuf};
 use structopt::StructOpt;
 
+mod api;
 mod docker;
 mod errors;
 
@@ -16,6 +17,20 @@ use errors::Result;
 #[derive(Debug, PartialEq, StructOpt)]
 #[structopt(about = "align images on the sky")]
 struct AlignerFrontendOptions {
+    #[structopt(
+        long = "image",
+        global = true,
+        help = "Use a specific Docker image reference instead of the default"
+    )]
+    image: Option<String>,
+
+    #[structopt(
+        long = "verbose",
+        global = true,
+        help = "Report additional diagnostic information"
+    )]
+    verbose: bool,
+
     #[structopt(subcommand)]
     command: Commands,
 }
@@ -51,6 +66,7 @@ impl Command for Commands {
 
 fn main() {
     let opts = AlignerFrontendOptions::from_args();
+    docker::configure(opts.image, opts.verbose);
     std::process::exit(errors::report(opts.command.execute()));
 }
 
@@ -66,27 +82,62 @@ fn do_other(all_args: Vec<OsString>) -> Result<i32> {
         ["failed to validate command-line arguments"]
     );
 
-    let db = match db {
+    let mut db = match db {
         docker::AnalysisOutcome::Continue(c) => c,
         docker::AnalysisOutcome::EarlyExit(c) => return Ok(c),
     };
 
-    let mut cmd = db.into_command();
-    let status = atry!(
-        cmd.status();
-        ["failed to launch the Docker command: {:?}", cmd]
+    let engine = db.engine();
+
+    // In remote mode this creates named volumes and stages the input files
+    // into them; the returned guard tears those resources down when dropped.
+    let mut guard = atry!(
+        db.prepare();
+        ["failed to prepare the remote data volumes"]
     );
 
-    let c = match status.code() {
-        Some(0) => 0,
-        Some(c) => {
-            eprintln!("error: the Docker command signaled failure");
-            c
-        }
-        None => {
-            eprintln!("error: the Docker command exited unexpectedly");
-            1
+    let c = if db.is_remote() {
+        // Remote runs still go through the CLI: we need the container to
+        // outlive its exit so that `docker cp` can copy the staged outputs back
+        // out before it is removed.
+        let mut cmd = atry!(
+            db.into_command(guard.as_mut());
+            ["failed to construct the {} command", engine]
+        );
+        let status = atry!(
+            cmd.status();
+            ["failed to launch the {} command: {:?}", engine, cmd]
+        );
+
+        let c = match status.code() {
+            Some(0) => 0,
+            Some(c) => {
+                eprintln!("error: the {} command signaled failure", engine);
+                c
+            }
+            None => {
+                eprintln!("error: the {} command exited unexpectedly", engine);
+                1
+            }
+        };
+
+        // On success, copy any files the command created back out onto the host.
+        if c == 0 {
+            if let Some(g) = guard.as_ref() {
+                atry!(
+                    g.collect_outputs();
+                    ["failed to copy the command's output files back to the host"]
+                );
+            }
         }
+
+        c
+    } else {
+        // Local runs talk to the daemon directly over its HTTP API.
+        atry!(
+            db.run_via_api();
+            ["failed to run the command through the {} API", engine]
+        )
     };
 
     Ok(c)
@@ -133,7 +184,10 @@ impl Command for PreviewCommand {
         );
 
         // We can't use `do_other()` here since we shouldn't wait for the
-        // command to finish running -- it only exits on SIGINT.
+        // command to finish running -- it only exits on SIGINT. That also
+        // means we can't route it through the HTTP API's run-to-completion
+        // `run_container`: this still shells out to the CLI and spawns the
+        // server container in the background.
 
         let serve_wtml_args = vec![
             "serve-wtml".into(),
@@ -146,15 +200,25 @@ impl Command for PreviewCommand {
             ["failed to validate command-line arguments"]
         );
 
-        let db = match db {
+        let mut db = match db {
             docker::AnalysisOutcome::Continue(c) => c,
             docker::AnalysisOutcome::EarlyExit(c) => return Ok(c),
         };
 
-        let mut cmd = db.into_command();
+        let engine = db.engine();
+
+        let mut guard = atry!(
+            db.prepare();
+            ["failed to prepare the remote data volumes"]
+        );
+
+        let mut cmd = atry!(
+            db.into_command(guard.as_mut());
+            ["failed to construct the {} command", engine]
+        );
         let mut child = atry!(
             cmd.spawn();
-            ["failed to launch the Docker command: {:?}", cmd]
+            ["failed to launch the {} command: {:?}", engine, cmd]
         );
 
         // There's a minor race here since we don't know when the child HTTP
@@ -193,31 +257,17 @@ struct UpdateCommand {
 impl Command for UpdateCommand {
     fn execute(self) -> Result<i32> {
         let tag = if self.latest { "latest" } else { "stable" };
+        let engine = docker::Engine::get()?;
 
-        println!("Updating the Docker image to tag \"{}\" ...", tag);
+        println!("Updating the {} image to tag \"{}\" ...", engine, tag);
         println!();
 
-        for mut cmd in docker::update_commands(tag).drain(..) {
-            let status = atry!(
-                cmd.status();
-                ["failed to launch the Docker command: {:?}", cmd]
-            );
-
-            match status.code() {
-                Some(0) => {}
-                Some(c) => {
-                    eprintln!("error: the Docker command signaled failure");
-                    return Ok(c);
-                }
-                None => {
-                    eprintln!("error: the Docker command exited unexpectedly");
-                    return Ok(1);
-                }
-            };
-
-            println!();
-        }
+        atry!(
+            docker::pull_image(tag);
+            ["failed to update the {} image", engine]
+        );
 
+        println!();
         println!("Done!");
         Ok(0)
     }